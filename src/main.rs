@@ -4,26 +4,66 @@ use async_std::task::sleep;
 use dioxus::{core::to_owned, prelude::*};
 use dioxus_helmet::Helmet;
 use instant::*;
+use serde::{Deserialize, Serialize};
 use std::fmt::Display;
 use wasm_bindgen::__rt::Start;
 use web_sys::HtmlAudioElement;
 
 const PUBLIC_URL: &str = "/";
 
+#[derive(Clone, Copy, PartialEq)]
+enum RestKind {
+    Short,
+    Long,
+}
+
+/// An active countdown phase: focused work, or a (short/long) break.
+#[derive(Clone, Copy, PartialEq)]
+enum Phase {
+    Working,
+    Resting(RestKind),
+}
+
 #[derive(Clone, Copy)]
 enum TimerState {
     Inactive,
-    Working,
-    Resting,
-    Paused(Instant),
+    /// Counting down towards `PomoTimer::deadline` in the given phase.
+    Running(Phase),
+    /// Frozen mid-phase; remembers what to resume into and how much was left.
+    Paused {
+        resume_to: Phase,
+        time_remaining: Duration,
+    },
 }
 
 #[derive(Clone, Copy)]
 struct PomoTimer {
     work_duration: Duration,
     rest_duration: Duration,
+    long_rest_duration: Duration,
+    /// How far each `postpone()` pushes a break's deadline back.
+    postpone_duration: Duration,
+    /// Upper bound on time added by postponing within a single break.
+    max_postpone_per_break: Duration,
     deadline: Instant,
     state: TimerState,
+    /// Work sessions finished since the last long break reset.
+    completed_sessions: u32,
+    /// Work sessions between long breaks (classic Pomodoro uses 4).
+    sessions_per_long_break: u32,
+    /// When set, a finished `Working` phase rings and counts overtime upward
+    /// instead of auto-flipping into the break.
+    overrun: bool,
+    /// Instant the active deadline was crossed, while overtime is running.
+    overtime_since: Option<Instant>,
+    /// Number of postpones applied to the current break.
+    postpones: u32,
+    /// Time already added by postponing within the current break.
+    postponed_total: Duration,
+    /// Wall-clock start of the work session in progress, if any (epoch ms).
+    work_started_at: Option<f64>,
+    /// A just-finished work session waiting to be drained into the log.
+    last_event: Option<SessionRecord>,
 }
 
 impl PomoTimer {
@@ -35,8 +75,19 @@ impl PomoTimer {
         PomoTimer {
             work_duration,
             rest_duration,
+            long_rest_duration: Duration::from_secs(10 * 60),
+            postpone_duration: Duration::from_secs(2 * 60),
+            max_postpone_per_break: Duration::from_secs(15 * 60),
             deadline,
             state: TimerState::Inactive,
+            completed_sessions: 0,
+            sessions_per_long_break: 4,
+            overrun: false,
+            overtime_since: None,
+            postpones: 0,
+            postponed_total: Duration::ZERO,
+            work_started_at: None,
+            last_event: None,
         }
     }
 
@@ -49,43 +100,148 @@ impl PomoTimer {
                 self.deadline = Instant::now()
                     .checked_add(self.work_duration)
                     .unwrap_or_else(Instant::now);
-                self.state = TimerState::Working;
+                self.work_started_at = Some(now());
+                self.state = TimerState::Running(Phase::Working);
             }
-            TimerState::Paused(paused_at) => {
-                self.deadline += Instant::now()
-                    .checked_duration_since(paused_at)
-                    .unwrap_or(Duration::ZERO);
-                // FIXME: Incorrect if paused during rest
-                self.state = TimerState::Working;
+            TimerState::Paused {
+                resume_to,
+                time_remaining,
+            } => {
+                self.deadline = Instant::now() + time_remaining;
+                self.state = TimerState::Running(resume_to);
             }
             _ => (),
         }
     }
 
     fn stop(&mut self) {
-        match self.state {
-            TimerState::Working | TimerState::Resting => {
-                self.state = TimerState::Paused(Instant::now())
-            }
-            _ => (),
+        if let TimerState::Running(phase) = self.state {
+            self.overtime_since = None;
+            let time_remaining = self
+                .deadline
+                .checked_duration_since(Instant::now())
+                .unwrap_or(Duration::ZERO);
+            self.state = TimerState::Paused {
+                resume_to: phase,
+                time_remaining,
+            };
         }
     }
 
     fn reset(&mut self) {
+        // An in-progress work session that gets reset counts as abandoned.
+        if let Some(start) = self.work_started_at.take() {
+            self.last_event = Some(SessionRecord::new(start, self.work_duration, false));
+        }
+        self.overtime_since = None;
         self.state = TimerState::Inactive;
     }
 
-    fn update(&mut self) {
+    /// Resets the Pomodoro cycle counter back to the start.
+    fn reset_cycle(&mut self) {
+        self.completed_sessions = 0;
+    }
+
+    /// Pushes the current break's deadline back by `postpone_duration`,
+    /// staying in the same phase, up to `max_postpone_per_break` total.
+    fn postpone(&mut self) {
+        if let TimerState::Running(Phase::Resting(_)) = self.state {
+            if self.postponed_total + self.postpone_duration > self.max_postpone_per_break {
+                return;
+            }
+            self.deadline += self.postpone_duration;
+            self.postponed_total += self.postpone_duration;
+            self.postpones += 1;
+        }
+    }
+
+    /// Drains the pending session record, if a phase boundary produced one.
+    fn take_event(&mut self) -> Option<SessionRecord> {
+        self.last_event.take()
+    }
+
+    /// Estimated time until the next long break, counting the work sessions
+    /// (and short breaks between them) still owed in this cycle.
+    fn time_until_long_break(&self) -> Duration {
+        let (done, total) = self.cycle();
+        let work_left = total.saturating_sub(done);
+        if work_left == 0 {
+            return Duration::ZERO;
+        }
+        // Base counts the `work_left` work sessions still owed and the short
+        // rests between them, i.e. the schedule as seen from a work phase.
         match self.state {
-            TimerState::Working | TimerState::Resting => {
-                if self.time_left().is_zero() {
-                    self.flip();
+            // Mid-work: the base already includes this full session, so drop
+            // the part of it that has already elapsed.
+            TimerState::Running(Phase::Working) => {
+                let base = self.work_duration * work_left
+                    + self.rest_duration * work_left.saturating_sub(1);
+                let elapsed = self.work_duration.saturating_sub(self.time_left());
+                base.saturating_sub(elapsed)
+            }
+            // Mid-break: the base covers only what follows this break, so add
+            // what is left of the current rest on top of it.
+            TimerState::Running(Phase::Resting(_)) => {
+                self.work_duration * work_left
+                    + self.rest_duration * work_left.saturating_sub(1)
+                    + self.time_left()
+            }
+            _ => {
+                self.work_duration * work_left + self.rest_duration * work_left.saturating_sub(1)
+            }
+        }
+    }
+
+    /// Full countdown length of the given phase.
+    fn phase_duration(&self, phase: Phase) -> Duration {
+        match phase {
+            Phase::Working => self.work_duration,
+            Phase::Resting(RestKind::Short) => self.rest_duration,
+            Phase::Resting(RestKind::Long) => self.long_rest_duration,
+        }
+    }
+
+    /// Position within the current long-break cycle, as `(done, total)`.
+    fn cycle(&self) -> (u32, u32) {
+        let per = self.sessions_per_long_break.max(1);
+        (self.completed_sessions % per, per)
+    }
+
+    fn update(&mut self) {
+        if let TimerState::Running(phase) = self.state {
+            if self.time_left().is_zero() {
+                match phase {
+                    // Don't auto-flip work: ring once and start overrunning
+                    // until the user acknowledges with a flip.
+                    Phase::Working if self.overrun => {
+                        if self.overtime_since.is_none() {
+                            self.overtime_since = Some(Instant::now());
+                            self.ring();
+                        }
+                    }
+                    _ => self.flip(),
                 }
             }
-            _ => (),
         }
     }
 
+    /// Whether the current phase has run past its deadline in overrun mode.
+    fn is_overtime(&self) -> bool {
+        self.overtime_since.is_some()
+    }
+
+    /// Time elapsed since the deadline was crossed, while overrunning.
+    fn overtime(&self) -> Duration {
+        self.overtime_since
+            .and_then(|since| Instant::now().checked_duration_since(since))
+            .unwrap_or(Duration::ZERO)
+    }
+
+    /// Toggles the optional ring-and-overrun behaviour.
+    fn toggle_overrun(&mut self) {
+        self.overrun = !self.overrun;
+    }
+
     fn time_left(&self) -> Duration {
         self.deadline
             .checked_duration_since(Instant::now())
@@ -125,34 +281,54 @@ impl PomoTimer {
         }
     }
 
-    /// Flips the state of this [`PomoTimer`] and extends the deadline
+    /// Flips the state of this [`PomoTimer`] and extends the deadline.
+    ///
+    /// Leaving `Working` completes a session; every
+    /// `sessions_per_long_break`th one enters a long rest instead of the
+    /// usual short one.
     fn flip(&mut self) {
-        self.deadline = match self.state {
-            TimerState::Working => {
-                self.state = TimerState::Resting;
-                Instant::now() + self.rest_duration
-            }
-            TimerState::Resting => {
-                self.state = TimerState::Working;
-                Instant::now() + self.work_duration
-            }
-            TimerState::Inactive => {
-                self.state = TimerState::Working;
-                Instant::now() + self.work_duration
-            }
-            TimerState::Paused(_) => {
-                // FIXME: incorrect if paused at rest
-                self.state = TimerState::Working;
-                Instant::now() + self.work_duration
+        // Which phase are we leaving? A paused timer flips the phase it would
+        // have resumed into, an inactive one simply kicks off work.
+        let leaving = match self.state {
+            TimerState::Running(phase) => Some(phase),
+            TimerState::Paused { resume_to, .. } => Some(resume_to),
+            TimerState::Inactive => None,
+        };
+
+        // Flipping ends the current phase: acknowledge any overtime and clear
+        // the break's postpone tally so it never leaks into the next phase.
+        self.overtime_since = None;
+        self.postpones = 0;
+        self.postponed_total = Duration::ZERO;
+
+        let next = match leaving {
+            Some(Phase::Working) => {
+                self.completed_sessions += 1;
+                // A work phase that flips over ran to completion.
+                if let Some(start) = self.work_started_at.take() {
+                    self.last_event = Some(SessionRecord::new(start, self.work_duration, true));
+                }
+                let long = self.sessions_per_long_break != 0
+                    && self.completed_sessions % self.sessions_per_long_break == 0;
+                Phase::Resting(if long { RestKind::Long } else { RestKind::Short })
             }
+            Some(Phase::Resting(_)) => Phase::Working,
+            None => Phase::Working,
         };
+
+        if next == Phase::Working {
+            self.work_started_at = Some(now());
+        }
+
+        self.deadline = Instant::now() + self.phase_duration(next);
+        self.state = TimerState::Running(next);
         self.ring();
     }
 
     fn toggle_pause(&mut self) {
         match self.state {
-            TimerState::Working | TimerState::Resting => self.stop(),
-            TimerState::Paused(_) | TimerState::Inactive => self.start(),
+            TimerState::Running(_) => self.stop(),
+            TimerState::Paused { .. } | TimerState::Inactive => self.start(),
         }
     }
 
@@ -167,13 +343,15 @@ impl PomoTimer {
 
 impl Display for PomoTimer {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_overtime() {
+            let overtime = self.overtime();
+            return write!(f, "+{}:{:0>2}", overtime.as_secs() / 60, overtime.as_secs() % 60);
+        }
+
         let time_left = match self.state {
-            TimerState::Paused(paused_at) => self
-                .deadline
-                .checked_duration_since(paused_at)
-                .unwrap_or(Duration::ZERO),
+            TimerState::Paused { time_remaining, .. } => time_remaining,
             TimerState::Inactive => self.work_duration,
-            _ => self.time_left(),
+            TimerState::Running(_) => self.time_left(),
         };
         let minutes_left = time_left.as_secs() / 60;
         let secs_left = time_left.as_secs() % 60;
@@ -182,18 +360,162 @@ impl Display for PomoTimer {
     }
 }
 
+/// A single work session as recorded for analytics.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct SessionRecord {
+    /// Start of the session, epoch milliseconds.
+    start: f64,
+    /// Intended length in seconds.
+    intended_secs: u64,
+    /// `true` if the session ran to completion, `false` if reset/abandoned.
+    completed: bool,
+}
+
+impl SessionRecord {
+    fn new(start: f64, intended: Duration, completed: bool) -> Self {
+        SessionRecord {
+            start,
+            intended_secs: intended.as_secs(),
+            completed,
+        }
+    }
+}
+
+const STORAGE_KEY: &str = "pomo.sessions";
+
+/// Append-only log of work sessions, mirrored to `localStorage`.
+#[derive(Clone, Default)]
+struct SessionLog {
+    records: Vec<SessionRecord>,
+}
+
+impl SessionLog {
+    /// Loads a previously persisted log, or an empty one.
+    fn load() -> Self {
+        let records = local_storage()
+            .and_then(|storage| storage.get_item(STORAGE_KEY).ok().flatten())
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+            .unwrap_or_default();
+        SessionLog { records }
+    }
+
+    fn record(&mut self, record: SessionRecord) {
+        self.records.push(record);
+        self.persist();
+    }
+
+    fn clear(&mut self) {
+        self.records.clear();
+        self.persist();
+    }
+
+    fn persist(&self) {
+        if let (Some(storage), Ok(raw)) =
+            (local_storage(), serde_json::to_string(&self.records))
+        {
+            let _ = storage.set_item(STORAGE_KEY, &raw);
+        }
+    }
+
+    /// Pomodoros that ran to completion.
+    fn completed_count(&self) -> usize {
+        self.records.iter().filter(|r| r.completed).count()
+    }
+
+    /// Total intended focus time of completed sessions started today.
+    fn focused_today(&self) -> Duration {
+        let today = day_bucket(now());
+        let secs = self
+            .records
+            .iter()
+            .filter(|r| r.completed && day_bucket(r.start) == today)
+            .map(|r| r.intended_secs)
+            .sum();
+        Duration::from_secs(secs)
+    }
+
+    /// Length of the trailing run of completed sessions.
+    fn streak(&self) -> usize {
+        self.records.iter().rev().take_while(|r| r.completed).count()
+    }
+}
+
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
+
+/// Day index since the epoch, used to bucket records into "today".
+fn day_bucket(ms: f64) -> i64 {
+    (ms / 86_400_000.0).floor() as i64
+}
+
+/// Formats a duration as `Hh Mm` / `Mm Ss` for the analytics panel.
+fn format_span(span: Duration) -> String {
+    let total = span.as_secs();
+    if total >= 3600 {
+        format!("{}h {}m", total / 3600, (total % 3600) / 60)
+    } else {
+        format!("{}m {}s", total / 60, total % 60)
+    }
+}
+
 fn App(cx: Scope) -> Element {
     use_context_provider::<PomoTimer>(&cx, || {
         PomoTimer::new(Duration::from_secs(25 * 60), Duration::from_secs(5 * 60))
     });
     let shared_timer = use_context::<PomoTimer>(&cx)?;
-
-    shared_timer.write().update();
+    use_context_provider::<SessionLog>(&cx, SessionLog::load);
+    let session_log = use_context::<SessionLog>(&cx)?;
+
+    // Bumped on every scheduled tick so the `Timer` subtree re-renders even
+    // when no key/click event happened.
+    let render_tick = use_state(&cx, || 0u64);
+
+    // Drive the countdown from a background task spawned on mount rather than
+    // relying on render events: wake every 250ms and, only while `Running`,
+    // advance the timer and nudge a render. We peek through `read()` first so
+    // that an idle (`Inactive`/`Paused`) timer never takes a `write()` lock and
+    // dirties every subscriber 4×/sec. `write()` is taken only when there is
+    // real work: a pending session record to drain, or an active countdown to
+    // tick.
+    use_future(&cx, (), |_| {
+        to_owned![shared_timer, session_log, render_tick];
+        async move {
+            loop {
+                sleep(Duration::from_millis(250)).await;
+                let (running, has_event) = {
+                    let timer = shared_timer.read();
+                    (
+                        matches!(timer.state, TimerState::Running(_)),
+                        timer.last_event.is_some(),
+                    )
+                };
+                if has_event {
+                    if let Some(record) = shared_timer.write().take_event() {
+                        session_log.write().record(record);
+                    }
+                }
+                if running {
+                    shared_timer.write().update();
+                    render_tick.set(render_tick.get() + 1);
+                }
+            }
+        }
+    });
 
     let state = shared_timer.write().state;
     let icon_path = match state {
-        TimerState::Inactive | TimerState::Working => "assets/icon_work.png",
-        TimerState::Resting | TimerState::Paused(_) => "assets/icon_rest.png",
+        TimerState::Inactive
+        | TimerState::Running(Phase::Working)
+        | TimerState::Paused {
+            resume_to: Phase::Working,
+            ..
+        } => "assets/icon_work.png",
+        TimerState::Running(Phase::Resting(_))
+        | TimerState::Paused {
+            resume_to: Phase::Resting(_),
+            ..
+        } => "assets/icon_rest.png",
     };
 
     cx.render(rsx! (
@@ -207,6 +529,9 @@ fn App(cx: Scope) -> Element {
                     "f" => shared_timer.write().flip(),
                     "i" => shared_timer.write().increase_duration(Duration::from_secs(5 * 60)),
                     "n" => shared_timer.write().reset(),
+                    "c" => shared_timer.write().reset_cycle(),
+                    "s" => shared_timer.write().postpone(),
+                    "o" => shared_timer.write().toggle_overrun(),
                     "d" => shared_timer.write().decrease_duration(Duration::from_secs(5 * 60)),
                     " " => shared_timer.write().toggle_pause(),
                     "p" => shared_timer.write().toggle_pause(),
@@ -217,8 +542,10 @@ fn App(cx: Scope) -> Element {
                 class: "w-96 p-1",
                 PageIcon { path: icon_path.to_owned() }
                 Timer { }
+                CycleCounter { }
                 TimerControls { }
                 HelpText { }
+                AnalyticsPanel { }
                 CreditsText { }
             }
         }
@@ -268,6 +595,9 @@ fn HelpText(cx: Scope) -> Element {
                     kbd { class: "{kbd_css}", "f" } " lip the timer" br { }
                     kbd { class: "{kbd_css}", "i" } " ncrease duration" br { }
                     kbd { class: "{kbd_css}", "n" } " ew timer" br { }
+                    kbd { class: "{kbd_css}", "c" } " ycle reset" br { }
+                    kbd { class: "{kbd_css}", "s" } " nooze break" br { }
+                    kbd { class: "{kbd_css}", "o" } " verrun toggle" br { }
                     kbd { class: "{kbd_css}", "d" } " ecrease duration" br { }
                     kbd { class: "{kbd_css}", "p" } " ause " br { }
                     kbd { class: "{kbd_css}", "Ctrl" } kbd { class: "{kbd_css}", "+" } " / "
@@ -318,19 +648,32 @@ fn TimerControls(cx: Scope) -> Element {
                 }
             )
         }
-        TimerState::Working | TimerState::Resting => {
+        TimerState::Running(phase) => {
+            let snooze = matches!(phase, Phase::Resting(_)).then(|| {
+                rsx!(
+                    button {
+                        class: "text-gray-500 hover:text-gray-700 border border-gray-800 focus:outline-none
+                                font-medium rounded-lg text-sm px-4 py-2.5 text-center
+                                m-1 dark:border-gray-600 dark:text-gray-400
+                                dark:hover:text-white dark:hover:bg-gray-600 dark:focus:ring-gray-800",
+                        onclick: move |_| shared_timer.write().postpone(),
+                        "Snooze"
+                    }
+                )
+            });
             rsx!(
                 button {
                     class: "w-1/2 text-gray-500 hover:text-gray-700 border border-gray-800 focus:outline-none
-                            font-medium rounded-lg text-sm px-5 py-2.5 text-center 
-                            m-1 dark:border-gray-600 dark:text-gray-400 
+                            font-medium rounded-lg text-sm px-5 py-2.5 text-center
+                            m-1 dark:border-gray-600 dark:text-gray-400
                             dark:hover:text-white dark:hover:bg-gray-600 dark:focus:ring-gray-800",
                     onclick: move |_| shared_timer.write().stop(),
                     "Pause"
                 }
+                snooze
             )
         }
-        TimerState::Paused(_) => {
+        TimerState::Paused { .. } => {
             rsx!(
                 button {
                     class: "w-1/2 text-purple-500 hover:text-purple-700 border border-purple-500 focus:outline-none
@@ -347,14 +690,114 @@ fn TimerControls(cx: Scope) -> Element {
     cx.render(rsx!(controls))
 }
 
+fn CycleCounter(cx: Scope) -> Element {
+    let shared_timer = use_context::<PomoTimer>(&cx)?;
+    let timer = shared_timer.write();
+    match timer.state {
+        TimerState::Inactive => return None,
+        _ => (),
+    }
+    let (done, total) = timer.cycle();
+
+    cx.render(rsx!(
+        div {
+            class: "text-gray-700 font-medium",
+            "Pomodoro {done}/{total} "
+            button {
+                class: "text-gray-500 hover:text-gray-700 underline decoration-gray-400",
+                onclick: move |_| shared_timer.write().reset_cycle(),
+                "reset"
+            }
+        }
+    ))
+}
+
+fn AnalyticsPanel(cx: Scope) -> Element {
+    let session_log = use_context::<SessionLog>(&cx)?;
+    let open = use_state(&cx, || false);
+
+    if !*open.get() {
+        return cx.render(rsx!(
+            div {
+                class: "pt-3",
+                button {
+                    class: "text-sm text-gray-600 hover:text-gray-800 underline decoration-gray-400",
+                    onclick: move |_| open.set(true),
+                    "Show stats"
+                }
+            }
+        ));
+    }
+
+    let log = session_log.read();
+    let focused = format_span(log.focused_today());
+    let completed = log.completed_count();
+    let streak = log.streak();
+
+    cx.render(rsx!(
+        div {
+            class: "flex flex-col text-center text-gray-700 pt-3",
+            div {
+                class: "flex justify-between",
+                span { class: "font-medium", "Focus stats" }
+                button {
+                    class: "text-sm text-gray-600 hover:text-gray-800 underline decoration-gray-400",
+                    onclick: move |_| open.set(false),
+                    "Hide"
+                }
+            }
+            div {
+                class: "text-left text-gray-700",
+                div { "Focused today: {focused}" }
+                div { "Completed pomodoros: {completed}" }
+                div { "Current streak: {streak}" }
+                // Static metrics above; the projection ticks on its own below.
+                Projection { }
+            }
+            button {
+                class: "pt-1 text-sm text-gray-500 hover:text-red-600 underline decoration-gray-400",
+                onclick: move |_| session_log.write().clear(),
+                "Clear history"
+            }
+        }
+    ))
+}
+
+/// Live projection of the time remaining until the next long break. Kept in
+/// its own component because it re-renders every tick while the timer runs,
+/// unlike the static metrics beside it.
+fn Projection(cx: Scope) -> Element {
+    let shared_timer = use_context::<PomoTimer>(&cx)?;
+    let timer = shared_timer.write();
+    match timer.state {
+        TimerState::Running(phase) => {
+            let until = format_span(timer.time_until_long_break());
+            let postpones = timer.postpones;
+            let snoozed = (matches!(phase, Phase::Resting(_)) && postpones > 0)
+                .then(|| rsx!(div { "Snoozed {postpones}× this break" }));
+            cx.render(rsx!(
+                div { "Until long break: {until}" }
+                snoozed
+            ))
+        }
+        _ => None,
+    }
+}
+
 fn Timer(cx: Scope) -> Element {
     let shared_timer = use_context::<PomoTimer>(&cx)?;
-    let mut timer = shared_timer.write();
+    let timer = shared_timer.write();
+
+    // Overtime gets a distinct red gradient so the overflow stands out.
+    let gradient = if timer.is_overtime() {
+        "bg-gradient-to-r from-red-400 to-pink-600"
+    } else {
+        "bg-gradient-to-r from-purple-400 to-pink-600"
+    };
 
     cx.render(rsx! (
         h1 {
-            class: "font-extrabold font-sans text-transparent text-8xl
-                    bg-clip-text bg-gradient-to-r from-purple-400 to-pink-600",
+            class: "font-extrabold font-sans text-transparent text-8xl bg-clip-text {gradient}",
             "{timer}"
         }
     ))